@@ -0,0 +1,424 @@
+//! A serde `Deserializer` for the event-data format.
+//!
+//! `parse_event_data` only ever hands back an untyped `serde_json::Value`.
+//! This module walks the same grammar but drives a serde `Visitor` directly,
+//! so callers can `from_str::<MyStruct>(data)` into a typed struct instead,
+//! the way nu-json's Hjson deserializer sits alongside its own untyped
+//! parser.
+
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+
+use crate::read::{Read as _, SliceRead};
+use crate::{parse_key, parse_string, skip_leading_whitespace, ParseError};
+
+/// Deserializes an instance of `T` from a string of event data.
+///
+/// Exercised by this module's tests; not yet called from `main`, which only
+/// ever needs the untyped `serde_json::Value` that `parse_event_data` hands
+/// back.
+#[allow(dead_code)]
+pub fn from_str<'de, T>(input: &'de str) -> Result<T, ParseError>
+where
+    T: de::Deserialize<'de>,
+{
+    let mut deserializer = Deserializer::from_str(input);
+    T::deserialize(&mut deserializer)
+}
+
+pub struct Deserializer<'de> {
+    reader: SliceRead<'de>,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn from_str(input: &'de str) -> Self {
+        Deserializer {
+            reader: SliceRead::new(input.as_bytes()),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = ParseError;
+
+    /// The grammar has the same three shapes as [`parse_value`](crate::parse_value):
+    /// a `{ ... }` object, a `[ ... ]` array, or a quoted string. A top-level
+    /// call additionally accepts a bare run of `key: value` pairs with no
+    /// enclosing braces, which `deserialize_map` treats as a fourth, EOF-
+    /// terminated shape. Peeking at the next non-whitespace byte is enough to
+    /// tell which one we're looking at.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        skip_leading_whitespace(&mut self.reader)?;
+        match self.reader.peek()? {
+            Some(b'"') => self.deserialize_str(visitor),
+            Some(b'[') => self.deserialize_seq(visitor),
+            _ => self.deserialize_map(visitor),
+        }
+    }
+
+    /// Consumes a leading `{` if present, the way a nested object does;
+    /// otherwise treats the input as the bare, brace-less run of `key:
+    /// value` pairs that only appears at the top level.
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        skip_leading_whitespace(&mut self.reader)?;
+        let end = match self.reader.peek()? {
+            Some(b'{') => {
+                self.reader.discard();
+                MapEnd::Brace
+            }
+            _ => MapEnd::Eof,
+        };
+        visitor.visit_map(EventDataMap { de: self, end })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        skip_leading_whitespace(&mut self.reader)?;
+        match self.reader.peek()? {
+            Some(b'[') => self.reader.discard(),
+            _ => {
+                return Err(ParseError::InvalidValue {
+                    position: self.reader.position(),
+                })
+            }
+        }
+        visitor.visit_seq(EventDataSeq { de: self })
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        let value = parse_string(&mut self.reader)?;
+        visitor.visit_string(value)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    /// Consumes and discards a value without allocating, the way
+    /// destream_json does, so `#[serde(skip)]` fields and unrecognized keys
+    /// can be parsed over without paying for a `String`.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        skip_value(&mut self.reader)?;
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf option unit unit_struct newtype_struct tuple
+        tuple_struct enum identifier
+    }
+}
+
+/// How an [`EventDataMap`] knows it has run out of pairs: a nested object
+/// ends at its closing `}`, while the brace-less top level just runs to EOF.
+#[derive(Clone, Copy)]
+enum MapEnd {
+    Eof,
+    Brace,
+}
+
+struct EventDataMap<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    end: MapEnd,
+}
+
+impl<'de, 'a> MapAccess<'de> for EventDataMap<'a, 'de> {
+    type Error = ParseError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, ParseError>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        skip_leading_whitespace(&mut self.de.reader)?;
+        let done = match self.end {
+            MapEnd::Eof => self.de.reader.peek()?.is_none(),
+            MapEnd::Brace => self.de.reader.peek()? == Some(b'}'),
+        };
+        if done {
+            if matches!(self.end, MapEnd::Brace) {
+                self.de.reader.discard(); // consume '}'
+            }
+            return Ok(None);
+        }
+
+        let key = parse_key(&mut self.de.reader)?;
+        seed.deserialize(de::value::StringDeserializer::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, ParseError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(&mut *self.de)?;
+
+        skip_leading_whitespace(&mut self.de.reader)?;
+        if self.de.reader.peek()? == Some(b',') {
+            self.de.reader.discard();
+        }
+
+        Ok(value)
+    }
+}
+
+struct EventDataSeq<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for EventDataSeq<'a, 'de> {
+    type Error = ParseError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, ParseError>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        skip_leading_whitespace(&mut self.de.reader)?;
+        if self.de.reader.peek()? == Some(b']') {
+            self.de.reader.discard();
+            return Ok(None);
+        }
+
+        let value = seed.deserialize(&mut *self.de)?;
+
+        skip_leading_whitespace(&mut self.de.reader)?;
+        if self.de.reader.peek()? == Some(b',') {
+            self.de.reader.discard();
+        }
+
+        Ok(Some(value))
+    }
+}
+
+/// Scans past a value without building it, mirroring
+/// [`parse_value`](crate::parse_value)'s dispatch on `{`/`[`/`"` but
+/// discarding bytes instead of collecting them.
+fn skip_value<R: crate::read::Read>(reader: &mut R) -> Result<(), ParseError> {
+    skip_leading_whitespace(reader)?;
+    match reader.peek()? {
+        Some(b'"') => skip_string(reader),
+        Some(b'{') => skip_object(reader),
+        Some(b'[') => skip_array(reader),
+        _ => Err(ParseError::InvalidValue {
+            position: reader.position(),
+        }),
+    }
+}
+
+/// Scans past one quoted value without building a `String`.
+fn skip_string<R: crate::read::Read>(reader: &mut R) -> Result<(), ParseError> {
+    match reader.peek()? {
+        Some(b'"') => reader.discard(),
+        _ => {
+            return Err(ParseError::InvalidValue {
+                position: reader.position(),
+            })
+        }
+    }
+
+    loop {
+        match reader.peek()? {
+            Some(b'"') => {
+                reader.discard();
+                break;
+            }
+            Some(b'\\') => {
+                // Skip the escaped character without decoding it; it can't
+                // contain an unescaped closing quote.
+                reader.discard();
+                reader.discard();
+            }
+            Some(_) => {
+                reader.discard();
+            }
+            None => {
+                return Err(ParseError::InvalidValue {
+                    position: reader.position(),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Scans past a `{ key: value, ... }` object without building a `Map`.
+fn skip_object<R: crate::read::Read>(reader: &mut R) -> Result<(), ParseError> {
+    reader.discard(); // consume '{'
+
+    loop {
+        skip_leading_whitespace(reader)?;
+        match reader.peek()? {
+            Some(b'}') => {
+                reader.discard();
+                break;
+            }
+            Some(_) => {
+                // Skip the key up to and including its ':' without collecting it.
+                loop {
+                    match reader.next()? {
+                        Some(b':') => break,
+                        Some(_) => {}
+                        None => {
+                            return Err(ParseError::InvalidKey {
+                                position: reader.position(),
+                            })
+                        }
+                    }
+                }
+                skip_value(reader)?;
+
+                skip_leading_whitespace(reader)?;
+                if reader.peek()? == Some(b',') {
+                    reader.discard();
+                }
+            }
+            None => {
+                return Err(ParseError::InvalidValue {
+                    position: reader.position(),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Scans past a `[ value, ... ]` array without building a `Vec`.
+fn skip_array<R: crate::read::Read>(reader: &mut R) -> Result<(), ParseError> {
+    reader.discard(); // consume '['
+
+    loop {
+        skip_leading_whitespace(reader)?;
+        match reader.peek()? {
+            Some(b']') => {
+                reader.discard();
+                break;
+            }
+            Some(_) => {
+                skip_value(reader)?;
+
+                skip_leading_whitespace(reader)?;
+                if reader.peek()? == Some(b',') {
+                    reader.discard();
+                }
+            }
+            None => {
+                return Err(ParseError::InvalidValue {
+                    position: reader.position(),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::from_str;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Inner {
+        c: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Outer {
+        a: String,
+        b: Inner,
+    }
+
+    #[test]
+    fn deserializes_flat_pairs() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Flat {
+            a: String,
+            b: String,
+        }
+
+        let flat: Flat = from_str(r#"a: "1" b: "2""#).unwrap();
+        assert_eq!(
+            flat,
+            Flat {
+                a: "1".to_string(),
+                b: "2".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn deserializes_nested_objects() {
+        let outer: Outer = from_str(r#"a: "1" b: { c: "2" }"#).unwrap();
+        assert_eq!(
+            outer,
+            Outer {
+                a: "1".to_string(),
+                b: Inner {
+                    c: "2".to_string(),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn deserializes_nested_arrays() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct WithArray {
+            a: Vec<String>,
+        }
+
+        let with_array: WithArray = from_str(r#"a: [ "1", "2", "3" ]"#).unwrap();
+        assert_eq!(
+            with_array,
+            WithArray {
+                a: vec!["1".to_string(), "2".to_string(), "3".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_keys() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct OnlyA {
+            a: String,
+        }
+
+        let only_a: OnlyA = from_str(r#"a: "1" b: { c: "2" } d: [ "3" ]"#).unwrap();
+        assert_eq!(
+            only_a,
+            OnlyA {
+                a: "1".to_string(),
+            }
+        );
+    }
+}
@@ -1,11 +1,15 @@
 use std::env;
 use std::fs::File;
-use std::io::prelude::*;
 use std::io::BufReader;
 
 use serde_json::Map;
 use thiserror::Error;
 
+mod de;
+mod read;
+
+use read::{IoRead, Read as _};
+
 // Part B hint is "Hello, try XOR with 0x17F".
 const XOR_KEY: u16 = 0x17F;
 
@@ -17,194 +21,444 @@ fn main() -> Result<(), anyhow::Error> {
     let path = &args[1];
     let mut object = parse_event_data(path)?;
 
-    let fifth_value = figure_fifth_value(&object);
+    let keys = ["one", "two", "three", "four"];
+    let next_values = extrapolate_event_values(&object, &keys, XOR_KEY, 1);
     let map = object.as_object_mut().unwrap();
-    let value_str = format!("0x{:x}", fifth_value);
+    let value_str = format!("0x{:x}", next_values[0]);
     map.insert("five".to_string(), value_str.into());
     println!("{}", serde_json::to_string_pretty(&object)?);
 
     Ok(())
 }
 
-/// Computes the fifth value and returns it
-///
+/// Decodes the hex string stored at each of `keys` in `object` by XOR-ing it
+/// with `xor_key`, [`extrapolate`]s `count` further terms of the resulting
+/// sequence, and re-XORs those terms back before returning them.
 ///
-///
-/// Prints a debug print to stderr, which helped me to figure out what the fifth value could be.
-/// Example output:
-/// ```
+/// Example output for this file's own `XOR_KEY` and `"one".."four"`:
+/// ```text
 /// one   0x154 0b101010100 43 +
 /// two   0x150 0b101010000 47 /
 /// three 0x14A 0b101001010 53 5
 /// four  0x144 0b101000100 59 ;
 /// ```
-///
-/// It seems to be an increasing sequence of integers (43, 47, 53, 59, ...)
-/// Increments are 4, 6, 6, ...
-///
-/// Simplest rule I could figure out is the following is as follows.
-///     x_{i+2} = x_{i+1} + index_of_first_mismatching_bit(x_i, x_{i+1}) * (i - 1)
-/// Here the function index_of_first_mismatching_bit returns an index starting from 1.
-/// Also i starts from 1.
-/// Example:
-/// x_3 = x_3 + index_of_first_mismatching_bit(x_1, x_2) * 2
-///     = 47 + index_of_first_mismatching_bit(43, 47) * 2
-///     = 47 + 3 * 2
-///     = 53
-/// x_4 = x_3 + index_of_first_mismatching_bit(x_2, x_3) * 3
-///     = 53 + index_of_first_mismatching_bit(47, 53) * 3
-///     = 53 + 2 * 3
-///     = 59
-/// x_5 = x_4 + index_of_first_mismatching_bit(x_3, x_4) * 4
-///     = 59 + index_of_first_mismatching_bit(53, 59) * 4
-///     = 59 + 2 * 4
-///     = 67
-fn figure_fifth_value(object: &serde_json::Value) -> u16 {
+/// It seems to be an increasing sequence of integers (43, 47, 53, 59, ...).
+fn extrapolate_event_values(
+    object: &serde_json::Value,
+    keys: &[&str],
+    xor_key: u16,
+    count: usize,
+) -> Vec<u16> {
     let object_members = object.as_object().expect("Given value was not an object.");
 
-    let keys = vec!["one", "two", "three", "four"];
-    let mut values = vec![];
+    let mut values = Vec::with_capacity(keys.len());
     for key in keys {
-        let value_str = object_members[key]
+        let value_str = object_members[*key]
             .as_str()
             .unwrap_or_else(|| panic!("Unexpected value for key \"{}\"", key));
         let value_str_trimmed = value_str.trim_start_matches("0x");
         let value = u16::from_str_radix(value_str_trimmed, 16)
             .unwrap_or_else(|_| panic!("Unexpected value for key \"{}\"", key));
-        let value_xor = value ^ XOR_KEY;
-        eprintln!(
-            "{:5} {} {:#b} xorred:{:#b} {} {}",
-            key,
-            value_str,
-            value,
-            value_xor,
-            value_xor,
-            char::from_u32(value_xor as u32).expect("TODO")
-        );
-        values.push(value_xor);
-    }
-
-    // Compute the fifth value.
-    let three = values[2];
-    let four = values[3];
-    let index_of_first_mismatching_bit = |a: u16, b: u16| (a ^ b).trailing_zeros() as u16 + 1;
-    (four + index_of_first_mismatching_bit(three, four) * 4) ^ XOR_KEY
+        values.push(value ^ xor_key);
+    }
+
+    extrapolate(&values, count)
+        .into_iter()
+        .map(|value| value ^ xor_key)
+        .collect()
+}
+
+/// Returns the 1-based index of the lowest bit at which `a` and `b` differ.
+fn first_mismatching_bit(a: u16, b: u16) -> u16 {
+    (a ^ b).trailing_zeros() as u16 + 1
+}
+
+/// Continues the integer sequence observed in `values` for `count` more
+/// terms, and returns just those new terms.
+///
+/// Given decoded terms `x_1..x_n`, the next term is
+///     x_{n+1} = x_n + first_mismatching_bit(x_{n-1}, x_n) * n
+/// Example, given `x_1..x_4 = 43, 47, 53, 59`:
+/// x_5 = x_4 + first_mismatching_bit(x_3, x_4) * 4
+///     = 59 + first_mismatching_bit(53, 59) * 4
+///     = 59 + 2 * 4
+///     = 67
+fn extrapolate(values: &[u16], count: usize) -> Vec<u16> {
+    assert!(
+        values.len() >= 2,
+        "need at least two terms to extrapolate further ones"
+    );
+
+    let mut terms = values.to_vec();
+    while terms.len() < values.len() + count {
+        let n = terms.len();
+        let next = terms[n - 1] + first_mismatching_bit(terms[n - 2], terms[n - 1]) * n as u16;
+        terms.push(next);
+    }
+
+    terms.split_off(values.len())
+}
+
+/// A position in the input, tracked as parsing progresses so that errors can
+/// point at where they occurred.
+///
+/// Mirrors serde_json's own `Position`/`LineColIterator`: `line` and `column`
+/// are both 1-based, and `column` resets to 1 after a `'\n'`.
+#[derive(Debug, Clone, Copy)]
+pub struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    pub(crate) fn start() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Advances past `byte`, updating `self` to reflect it.
+    pub(crate) fn advance(&mut self, byte: u8) {
+        self.offset += 1;
+        if byte == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
 }
 
 #[derive(Error, Debug)]
-enum ParseError {
-    #[error("invalid key")]
-    InvalidKey,
-    #[error("invalid value")]
-    InvalidValue,
+pub enum ParseError {
+    #[error("invalid key at line {line}, column {column} (offset {offset})", line = .position.line, column = .position.column, offset = .position.offset)]
+    InvalidKey { position: Position },
+    #[error("invalid value at line {line}, column {column} (offset {offset})", line = .position.line, column = .position.column, offset = .position.offset)]
+    InvalidValue { position: Position },
+    #[error("invalid escape at line {line}, column {column} (offset {offset})", line = .position.line, column = .position.column, offset = .position.offset)]
+    InvalidEscape { position: Position },
+    #[error("invalid unicode escape at line {line}, column {column} (offset {offset})", line = .position.line, column = .position.column, offset = .position.offset)]
+    InvalidUnicodeEscape { position: Position },
+    #[error("unpaired surrogate in unicode escape at line {line}, column {column} (offset {offset})", line = .position.line, column = .position.column, offset = .position.offset)]
+    UnpairedSurrogate { position: Position },
+    #[error("I/O error reading event data: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Custom(String),
 }
 
-/// Parses event data from a file
-fn parse_event_data(file_path: &str) -> Result<serde_json::Value, anyhow::Error> {
-    let mut file = BufReader::new(File::open(file_path).expect("Failed to open file"));
+impl serde::de::Error for ParseError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        ParseError::Custom(msg.to_string())
+    }
+}
 
-    let mut data = String::new();
-    file.read_to_string(&mut data)?;
+/// Parses event data from a file, streaming it through an [`IoRead`] rather
+/// than buffering the whole file into memory.
+fn parse_event_data(file_path: &str) -> Result<serde_json::Value, anyhow::Error> {
+    let file = File::open(file_path).expect("Failed to open file");
+    let mut reader = IoRead::new(BufReader::new(file));
 
-    let mut it = data.chars().peekable();
     let mut pairs = Map::new();
-    while let Some(&c) = it.peek() {
+    while let Some(byte) = reader.peek()? {
         // Skip whitespace before a possible key.
-        if c.is_whitespace() {
-            it.next();
+        if byte.is_ascii_whitespace() {
+            reader.discard();
             continue;
         }
 
-        let key = parse_key(&mut it)?;
-        let value = parse_value(&mut it)?;
+        let key = parse_key(&mut reader)?;
+        let value = parse_value(&mut reader)?;
 
-        pairs.insert(key, value.into());
+        pairs.insert(key, value);
     }
 
     Ok(serde_json::Value::Object(pairs))
 }
 
-/// Skips all leading white spaces of the given iterator
-fn skip_leading_whitespace(it: &mut std::iter::Peekable<std::str::Chars>) {
-    while let Some(&c) = it.peek() {
-        if !c.is_whitespace() {
+/// Skips all leading white spaces of the given reader
+pub(crate) fn skip_leading_whitespace<R: read::Read>(reader: &mut R) -> Result<(), ParseError> {
+    while let Some(byte) = reader.peek()? {
+        if !byte.is_ascii_whitespace() {
             break;
         }
-        it.next();
+        reader.discard();
+    }
+    Ok(())
+}
+
+/// Reads exactly four hex digits into a `u16` code unit, as used by a
+/// `\uXXXX` escape. Does not advance past the fourth digit's successor.
+fn parse_unicode_escape<R: read::Read>(reader: &mut R) -> Result<u16, ParseError> {
+    let mut code_unit = 0u16;
+    for _ in 0..4 {
+        let digit = reader
+            .peek()?
+            .and_then(|byte| char::from(byte).to_digit(16))
+            .ok_or(ParseError::InvalidUnicodeEscape {
+                position: reader.position(),
+            })?;
+        code_unit = code_unit * 16 + digit as u16;
+        reader.discard();
+    }
+    Ok(code_unit)
+}
+
+/// Decodes the `char` denoted by a `\uXXXX` escape whose code unit was
+/// `unit`, reading a trailing low surrogate's `\uXXXX` escape from `reader`
+/// if `unit` is a high surrogate.
+fn decode_unicode_escape<R: read::Read>(reader: &mut R, unit: u16) -> Result<char, ParseError> {
+    if (0xD800..=0xDBFF).contains(&unit) {
+        // High surrogate: it must be immediately followed by a low surrogate
+        // so the pair can be combined.
+        if reader.peek()? != Some(b'\\') {
+            return Err(ParseError::UnpairedSurrogate {
+                position: reader.position(),
+            });
+        }
+        reader.discard();
+        if reader.peek()? != Some(b'u') {
+            return Err(ParseError::UnpairedSurrogate {
+                position: reader.position(),
+            });
+        }
+        reader.discard();
+        let low = parse_unicode_escape(reader)?;
+        if !(0xDC00..=0xDFFF).contains(&low) {
+            return Err(ParseError::UnpairedSurrogate {
+                position: reader.position(),
+            });
+        }
+        let combined =
+            0x10000 + (u32::from(unit) - 0xD800) * 0x400 + (u32::from(low) - 0xDC00);
+        char::from_u32(combined).ok_or(ParseError::UnpairedSurrogate {
+            position: reader.position(),
+        })
+    } else if (0xDC00..=0xDFFF).contains(&unit) {
+        // A lone low surrogate with no preceding high surrogate.
+        Err(ParseError::UnpairedSurrogate {
+            position: reader.position(),
+        })
+    } else {
+        char::from_u32(u32::from(unit)).ok_or(ParseError::InvalidUnicodeEscape {
+            position: reader.position(),
+        })
+    }
+}
+
+/// Parses a value, which may be a quoted string, a `{ ... }` object nested
+/// to any depth, or a `[ ... ]` array of values.
+///
+/// Leading whitespace are ignored.
+pub(crate) fn parse_value<R: read::Read>(reader: &mut R) -> Result<serde_json::Value, ParseError> {
+    skip_leading_whitespace(reader)?;
+
+    match reader.peek()? {
+        Some(b'{') => parse_object(reader),
+        Some(b'[') => parse_array(reader),
+        Some(b'"') => parse_string(reader).map(serde_json::Value::String),
+        _ => Err(ParseError::InvalidValue {
+            position: reader.position(),
+        }),
+    }
+}
+
+/// Parses a `{ key: value, ... }` object, having already skipped leading
+/// whitespace.
+fn parse_object<R: read::Read>(reader: &mut R) -> Result<serde_json::Value, ParseError> {
+    reader.discard(); // consume '{'
+
+    let mut pairs = Map::new();
+    loop {
+        skip_leading_whitespace(reader)?;
+        match reader.peek()? {
+            Some(b'}') => {
+                reader.discard();
+                break;
+            }
+            Some(_) => {
+                let key = parse_key(reader)?;
+                let value = parse_value(reader)?;
+                pairs.insert(key, value);
+
+                skip_leading_whitespace(reader)?;
+                if reader.peek()? == Some(b',') {
+                    reader.discard();
+                }
+            }
+            None => {
+                return Err(ParseError::InvalidValue {
+                    position: reader.position(),
+                })
+            }
+        }
+    }
+
+    Ok(serde_json::Value::Object(pairs))
+}
+
+/// Parses a `[ value, ... ]` array, having already skipped leading
+/// whitespace.
+fn parse_array<R: read::Read>(reader: &mut R) -> Result<serde_json::Value, ParseError> {
+    reader.discard(); // consume '['
+
+    let mut values = Vec::new();
+    loop {
+        skip_leading_whitespace(reader)?;
+        match reader.peek()? {
+            Some(b']') => {
+                reader.discard();
+                break;
+            }
+            Some(_) => {
+                values.push(parse_value(reader)?);
+
+                skip_leading_whitespace(reader)?;
+                if reader.peek()? == Some(b',') {
+                    reader.discard();
+                }
+            }
+            None => {
+                return Err(ParseError::InvalidValue {
+                    position: reader.position(),
+                })
+            }
+        }
     }
+
+    Ok(serde_json::Value::Array(values))
 }
 
-/// Parses a value
+/// Parses a quoted string, decoding escape sequences.
 ///
 /// Leading whitespace are ignored.
-fn parse_value(it: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, ParseError> {
-    skip_leading_whitespace(it);
+pub(crate) fn parse_string<R: read::Read>(reader: &mut R) -> Result<String, ParseError> {
+    skip_leading_whitespace(reader)?;
 
     // Check for opening double quotes.
-    if let Some(&c) = it.peek() {
-        if c == '"' {
-            it.next();
-        } else {
-            return Err(ParseError::InvalidValue);
+    match reader.peek()? {
+        Some(b'"') => {
+            reader.discard();
+        }
+        _ => {
+            return Err(ParseError::InvalidValue {
+                position: reader.position(),
+            })
         }
-    } else {
-        return Err(ParseError::InvalidValue);
     }
 
-    let mut value = String::new();
+    let mut value = Vec::new();
 
     // Parse until we encounter closing double quotes.
-    while let Some(&c) = it.peek() {
-        if c == '"' {
-            it.next();
+    while let Some(byte) = reader.peek()? {
+        if byte == b'"' {
+            reader.discard();
             break;
         }
 
-        // Handle escaped double quote.
-        if c == '\\' {
-            // Encountered an escaped character.
-            // We assume the character to be double quotes.
-            it.next();
-            if let Some(&c) = it.peek() {
-                if c == '"' {
-                    value.push(c);
-                    it.next();
+        // Handle an escape sequence.
+        if byte == b'\\' {
+            reader.discard();
+            let escaped = match reader.peek()? {
+                Some(b'"') => b'"',
+                Some(b'\\') => b'\\',
+                Some(b'/') => b'/',
+                Some(b'b') => 0x08,
+                Some(b'f') => 0x0c,
+                Some(b'n') => b'\n',
+                Some(b'r') => b'\r',
+                Some(b't') => b'\t',
+                Some(b'u') => {
+                    reader.discard();
+                    let unit = parse_unicode_escape(reader)?;
+                    let c = decode_unicode_escape(reader, unit)?;
+                    let mut buf = [0u8; 4];
+                    value.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
                     continue;
                 }
-                return Err(ParseError::InvalidValue);
-            } else {
-                return Err(ParseError::InvalidValue);
-            }
+                _ => {
+                    return Err(ParseError::InvalidEscape {
+                        position: reader.position(),
+                    })
+                }
+            };
+            value.push(escaped);
+            reader.discard();
+            continue;
         }
 
-        value.push(c);
-        it.next();
+        value.push(byte);
+        reader.discard();
     }
 
-    Ok(value)
+    String::from_utf8(value).map_err(|_| ParseError::InvalidValue {
+        position: reader.position(),
+    })
 }
 
 /// Parses a key
 ///
 /// Leading whitespace are ignored.
-fn parse_key(it: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, ParseError> {
-    skip_leading_whitespace(it);
+pub(crate) fn parse_key<R: read::Read>(reader: &mut R) -> Result<String, ParseError> {
+    skip_leading_whitespace(reader)?;
 
-    let mut key = String::new();
+    let mut key = Vec::new();
 
-    while let Some(&c) = it.peek() {
-        if c == ':' {
-            it.next();
+    while let Some(byte) = reader.peek()? {
+        if byte == b':' {
+            reader.discard();
             break;
         }
 
-        key.push(c);
-        it.next();
+        key.push(byte);
+        reader.discard();
+    }
+
+    if reader.peek()?.is_none() {
+        return Err(ParseError::InvalidKey {
+            position: reader.position(),
+        });
+    }
+
+    String::from_utf8(key).map_err(|_| ParseError::InvalidKey {
+        position: reader.position(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::read::SliceRead;
+
+    use super::parse_string;
+
+    fn parse_str(input: &str) -> Result<String, super::ParseError> {
+        parse_string(&mut SliceRead::new(input.as_bytes()))
+    }
+
+    #[test]
+    fn parses_basic_escapes() {
+        assert_eq!(parse_str(r#""a\nb\tc""#).unwrap(), "a\nb\tc");
     }
 
-    if it.peek().is_none() {
-        return Err(ParseError::InvalidKey);
+    #[test]
+    fn parses_a_unicode_escape() {
+        assert_eq!(parse_str("\"caf\\u00e9\"").unwrap(), "café");
     }
 
-    Ok(key)
+    #[test]
+    fn parses_a_surrogate_pair() {
+        // 😀 is the UTF-16 surrogate pair for U+1F600 GRINNING FACE.
+        assert_eq!(parse_str("\"\\ud83d\\ude00\"").unwrap(), "\u{1F600}");
+    }
+
+    #[test]
+    fn rejects_an_unpaired_high_surrogate() {
+        assert!(matches!(
+            parse_str(r#""\ud83d""#),
+            Err(super::ParseError::UnpairedSurrogate { .. })
+        ));
+    }
 }
@@ -0,0 +1,124 @@
+//! A byte-oriented input abstraction for the parser, mirroring serde_json's
+//! internal `Read` trait.
+//!
+//! Rather than buffering an entire file into a `String` up front, the parser
+//! pulls bytes one at a time through this trait, so it only ever needs to
+//! keep the current key/value in memory, and has a single place to track
+//! [`Position`] for error reporting.
+
+use std::io;
+
+use crate::Position;
+
+mod private {
+    pub(crate) trait Sealed {}
+    impl Sealed for super::SliceRead<'_> {}
+    impl<R> Sealed for super::IoRead<R> {}
+}
+
+/// A source of bytes for the parser.
+///
+/// Sealed: only implemented for [`SliceRead`] and [`IoRead`] below.
+pub(crate) trait Read: private::Sealed {
+    fn next(&mut self) -> io::Result<Option<u8>>;
+    fn peek(&mut self) -> io::Result<Option<u8>>;
+    /// Discards the byte last returned by `peek`, if any. A no-op at EOF.
+    fn discard(&mut self);
+    fn position(&self) -> Position;
+}
+
+/// Reads from an in-memory byte slice.
+pub(crate) struct SliceRead<'a> {
+    slice: &'a [u8],
+    index: usize,
+    pos: Position,
+}
+
+impl<'a> SliceRead<'a> {
+    pub(crate) fn new(slice: &'a [u8]) -> Self {
+        SliceRead {
+            slice,
+            index: 0,
+            pos: Position::start(),
+        }
+    }
+}
+
+impl<'a> Read for SliceRead<'a> {
+    fn next(&mut self) -> io::Result<Option<u8>> {
+        match self.slice.get(self.index).copied() {
+            Some(byte) => {
+                self.index += 1;
+                self.pos.advance(byte);
+                Ok(Some(byte))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.slice.get(self.index).copied())
+    }
+
+    fn discard(&mut self) {
+        if let Some(&byte) = self.slice.get(self.index) {
+            self.index += 1;
+            self.pos.advance(byte);
+        }
+    }
+
+    fn position(&self) -> Position {
+        self.pos
+    }
+}
+
+/// Reads from any `std::io::Read`, such as a `BufReader<File>`.
+pub(crate) struct IoRead<R> {
+    reader: R,
+    peeked: Option<u8>,
+    pos: Position,
+}
+
+impl<R: io::Read> IoRead<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        IoRead {
+            reader,
+            peeked: None,
+            pos: Position::start(),
+        }
+    }
+
+    fn fill_peek(&mut self) -> io::Result<Option<u8>> {
+        if self.peeked.is_none() {
+            let mut byte = [0u8; 1];
+            self.peeked = match self.reader.read(&mut byte)? {
+                0 => None,
+                _ => Some(byte[0]),
+            };
+        }
+        Ok(self.peeked)
+    }
+}
+
+impl<R: io::Read> Read for IoRead<R> {
+    fn next(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.fill_peek()?;
+        if let Some(byte) = byte {
+            self.peeked = None;
+            self.pos.advance(byte);
+        }
+        Ok(byte)
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        self.fill_peek()
+    }
+
+    fn discard(&mut self) {
+        let _ = self.next();
+    }
+
+    fn position(&self) -> Position {
+        self.pos
+    }
+}